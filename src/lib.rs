@@ -77,6 +77,8 @@ extern crate libc;
 mod native;
 
 use std::error::Error;
+use std::ptr::null;
+use std::hash::{Hasher, BuildHasher};
 use std::fmt;
 use std::io;
 
@@ -85,6 +87,13 @@ use std::io;
 pub type Result<T> = std::result::Result<T, Md6Error>;
 
 
+/// The reference `md6_default_r()`: `40 + d/4` rounds, floored at `80` only for keyed states.
+fn default_r(hashbitlen: i32, keylen: usize) -> i32 {
+    let r = 40 + hashbitlen / 4;
+    if keylen > 0 { std::cmp::max(80, r) } else { r }
+}
+
+
 /// Hash all data in one fell swoop.
 ///
 /// Refer to individual functions for extended documentation.
@@ -122,6 +131,71 @@ pub fn hash(hashbitlen: i32, data: &[u8], hashval: &mut [u8]) -> Result<()> {
     }
 }
 
+/// Hash a bitstring in one fell swoop, passing through the exact bit count.
+///
+/// Like [`hash()`](fn.hash.html) but the message is `nbits` bits long rather than an implied whole
+/// number of bytes, so messages whose length is not a multiple of 8 (e.g. non-byte-aligned
+/// protocol fields) can be hashed. The final partial byte of `data` is interpreted MSB-first, as in
+/// the reference implementation.
+///
+/// `nbits` must not exceed `data.len() * 8`; this is checked with a debug assertion.
+///
+/// # Example
+///
+/// ```
+/// # use md6::Md6;
+/// # use std::iter::FromIterator;
+/// let mut result = [0; 32];
+/// // Hash only the top 12 bits of the two-byte message.
+/// md6::hash_bits(256, &[0xAB, 0xC0], 12, &mut result).unwrap();
+///
+/// assert_eq!(Vec::from_iter(result.iter().map(|&i| i)),
+///            vec![0xCA, 0x92, 0xEA, 0x23, 0xF6, 0x08, 0xAA, 0x3E,
+///                 0x93, 0xE8, 0x6E, 0xAE, 0x61, 0xC2, 0x7B, 0x38,
+///                 0xF2, 0xF8, 0x88, 0x42, 0x65, 0xB5, 0x35, 0xFF,
+///                 0xD7, 0x2C, 0xE5, 0x71, 0x2A, 0x29, 0x22, 0x94]);
+/// ```
+pub fn hash_bits(hashbitlen: i32, data: &[u8], nbits: u64, hashval: &mut [u8]) -> Result<()> {
+    debug_assert!(nbits <= data.len() as u64 * 8);
+    match unsafe { native::MD6_Hash_Hash(hashbitlen, data.as_ptr(), nbits, hashval.as_mut_ptr()) } {
+        0 => Ok(()),
+        e => Err(Md6Error::from(e)),
+    }
+}
+
+/// Hash all data in one fell swoop under the given key, producing a keyed hash / MAC.
+///
+/// Equivalent to [`hash()`](fn.hash.html) but the underlying `md6_state` is keyed via the
+/// reference `md6_full_init()` entry point, letting `key` (up to 64 bytes, e.g. drawn from a
+/// strong RNG) authenticate the message rather than merely digesting it.
+///
+/// Returns:
+///
+///   * `Err(Md6Error::BadKeylen)` if `key` is longer than 64 bytes,
+///   * `Err(Md6Error::BadHashbitlen)` if `hashbitlen` is out of range, or
+///   * `Ok(())` if hashing succeeds.
+///
+/// # Example
+///
+/// ```
+/// # use md6::Md6;
+/// # use std::iter::FromIterator;
+/// let mut result = [0; 32];
+/// md6::hash_keyed(256, b"s3cr3t key", b"The lazy fox jumps over the lazy dog", &mut result).unwrap();
+///
+/// assert_eq!(Vec::from_iter(result.iter().map(|&i| i)),
+///            vec![0xDF, 0xEC, 0x88, 0x29, 0x29, 0x11, 0xD4, 0xA2,
+///                 0xAF, 0xAC, 0xA7, 0x54, 0xB8, 0x0F, 0x13, 0xF4,
+///                 0x52, 0xBE, 0x59, 0xA0, 0x66, 0x14, 0x71, 0xAE,
+///                 0x5B, 0x14, 0x17, 0x02, 0x00, 0x89, 0xAE, 0xAF]);
+/// ```
+pub fn hash_keyed(hashbitlen: i32, key: &[u8], data: &[u8], hashval: &mut [u8]) -> Result<()> {
+    let mut state = Md6::with_key(hashbitlen, key)?;
+    state.update(data);
+    state.finalise(hashval);
+    Ok(())
+}
+
 /// Hashing state for multiple data sets.
 ///
 /// # Example
@@ -175,6 +249,8 @@ pub enum Md6Error {
     Fail,
     /// `hashbitlen` passed to `Md6::new()` or `hash()` incorrect
     BadHashbitlen,
+    /// `key` passed to `Md6::with_key()` or `hash_keyed()` longer than 64 bytes
+    BadKeylen,
 }
 
 
@@ -216,6 +292,87 @@ impl Md6 {
         }
     }
 
+    /// Create a new hash state keyed with the given key, for keyed hashing / MAC mode.
+    ///
+    /// `hashbitlen` is the hash output length, as in [`new()`](#method.new).
+    /// `key` is the MAC key and may be up to `64` bytes long; an empty key is equivalent to an
+    /// unkeyed hash.
+    ///
+    /// Returns:
+    ///
+    ///   * `Err(Md6Error::BadKeylen)` if `key` is longer than 64 bytes,
+    ///   * `Err(Md6Error::BadHashbitlen)` if `hashbitlen` is out of range, or
+    ///   * `Ok(Md6)` if initialisation succeeds.
+    ///
+    /// # Examples
+    ///
+    /// Over-long key
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// assert_eq!(Md6::with_key(256, &[0; 65]).map(|_| ()), Err(md6::Md6Error::BadKeylen));
+    /// ```
+    ///
+    /// Creating a keyed 256-long state
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// Md6::with_key(256, b"hunter2").unwrap();
+    /// ```
+    pub fn with_key(hashbitlen: i32, key: &[u8]) -> Result<Md6> {
+        if key.len() > 64 {
+            return Err(Md6Error::BadKeylen);
+        }
+
+        let mut raw_state = native::malloc_hash_state();
+
+        match unsafe { native::md6_full_init(raw_state, hashbitlen, key.as_ptr(), key.len() as i32, 64, default_r(hashbitlen, key.len())) } {
+            0 => Ok(Md6 { raw_state: raw_state }),
+            e => {
+                native::free_hash_state(&mut raw_state);
+                Err(Md6Error::from(e))
+            }
+        }
+    }
+
+    /// Create a new hash state with explicit control over MD6's tunable parameters.
+    ///
+    /// `hashbitlen` is the hash output length, as in [`new()`](#method.new).
+    /// `rounds` is the number of compression rounds `r`; `None` selects the reference default of
+    /// `40 + hashbitlen / 4` for this unkeyed path, lowering it trades security margin for speed
+    /// (reduced-round MD6).
+    /// `levels` is the mode/parallel-tree height `L`; `None` selects the reference default of `64`
+    /// (fully hierarchical), `Some(0)` forces pure-sequential mode and larger values bound the tree
+    /// height.
+    ///
+    /// Returns:
+    ///
+    ///   * `Err(Md6Error::BadHashbitlen)` if `hashbitlen` is out of range, or
+    ///   * `Ok(Md6)` if initialisation succeeds.
+    ///
+    /// # Examples
+    ///
+    /// A reduced-round, pure-sequential 256-long state
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// Md6::with_params(256, Some(40), Some(0)).unwrap();
+    /// ```
+    pub fn with_params(hashbitlen: i32, rounds: Option<i32>, levels: Option<i32>) -> Result<Md6> {
+        let r = rounds.unwrap_or_else(|| default_r(hashbitlen, 0));
+        let l = levels.unwrap_or(64);
+
+        let mut raw_state = native::malloc_hash_state();
+
+        match unsafe { native::md6_full_init(raw_state, hashbitlen, null(), 0, l, r) } {
+            0 => Ok(Md6 { raw_state: raw_state }),
+            e => {
+                native::free_hash_state(&mut raw_state);
+                Err(Md6Error::from(e))
+            }
+        }
+    }
+
     /// Append the provided data to the hash function.
     ///
     /// # Examples
@@ -251,6 +408,40 @@ impl Md6 {
     }
 
 
+    /// Append the first `nbits` bits of the provided data to the hash function.
+    ///
+    /// Like [`update()`](#method.update) but only `nbits` bits of `data` are consumed rather than
+    /// all `data.len() * 8`, so non-byte-aligned bitstrings can be fed in. The final partial byte is
+    /// interpreted MSB-first, as in the reference implementation.
+    ///
+    /// `nbits` must not exceed `data.len() * 8`; this is checked with a debug assertion. Note that,
+    /// as in the reference, MD6 only accepts further data while the last chunk was byte-aligned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// # use std::iter::FromIterator;
+    /// let mut result = [0; 32];
+    ///
+    /// let mut state = Md6::new(256).unwrap();
+    /// state.update_bits(&[0xAB, 0xC0], 12);
+    /// state.finalise(&mut result);
+    ///
+    /// assert_eq!(Vec::from_iter(result.iter().map(|&i| i)),
+    ///            vec![0xCA, 0x92, 0xEA, 0x23, 0xF6, 0x08, 0xAA, 0x3E,
+    ///                 0x93, 0xE8, 0x6E, 0xAE, 0x61, 0xC2, 0x7B, 0x38,
+    ///                 0xF2, 0xF8, 0x88, 0x42, 0x65, 0xB5, 0x35, 0xFF,
+    ///                 0xD7, 0x2C, 0xE5, 0x71, 0x2A, 0x29, 0x22, 0x94]);
+    /// ```
+    pub fn update_bits(&mut self, data: &[u8], nbits: u64) {
+        debug_assert!(nbits <= data.len() as u64 * 8);
+        unsafe {
+            native::MD6_Hash_Update(self.raw_state, data.as_ptr(), nbits);
+        }
+    }
+
+
     /// Finish hashing and store the output result in the provided space.
     ///
     /// The provided space must not be smaller than the hash function's size,
@@ -308,6 +499,102 @@ impl Md6 {
             native::MD6_Hash_Final(self.raw_state, hashval.as_mut_ptr());
         }
     }
+
+    /// Finish hashing, returning the digest in a correctly-sized `Vec`.
+    ///
+    /// Unlike [`finalise()`](#method.finalise), the output length is taken from the `d` the state
+    /// was created with, so there is no buffer to size by hand and no way to under-size it. Works
+    /// regardless of construction path, including [`with_key()`](#method.with_key)/
+    /// [`with_params()`](#method.with_params) states.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// let mut state = Md6::new(256).unwrap();
+    /// state.update(b"The lazy fox jumps over the lazy dog.");
+    /// assert_eq!(state.finalise_vec().len(), 32);
+    /// ```
+    ///
+    /// Keyed states are sized correctly too:
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// let mut state = Md6::with_key(256, b"s3cr3t key").unwrap();
+    /// state.update(b"The lazy fox jumps over the lazy dog");
+    /// assert_eq!(state.finalise_vec(),
+    ///            vec![0xDF, 0xEC, 0x88, 0x29, 0x29, 0x11, 0xD4, 0xA2,
+    ///                 0xAF, 0xAC, 0xA7, 0x54, 0xB8, 0x0F, 0x13, 0xF4,
+    ///                 0x52, 0xBE, 0x59, 0xA0, 0x66, 0x14, 0x71, 0xAE,
+    ///                 0x5B, 0x14, 0x17, 0x02, 0x00, 0x89, 0xAE, 0xAF]);
+    /// ```
+    pub fn finalise_vec(&mut self) -> Vec<u8> {
+        let mut hashval = vec![0; ((native::hash_state_d(self.raw_state) as usize) + 7) / 8];
+        self.finalise(&mut hashval);
+        hashval
+    }
+
+    /// Finish hashing, returning the lowercase hexadecimal digest.
+    ///
+    /// Reads the `hexhashval` buffer the reference fills during finalisation, sized from the
+    /// state's `d`, so it is correct for every construction path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// let mut state = Md6::new(256).unwrap();
+    /// assert_eq!(state.finalise_hex(),
+    ///            "bca38b24a804aa37d821d31af00f5598230122c5bbfc4c4ad5ed40e4258f04ca");
+    /// ```
+    ///
+    /// ```
+    /// # use md6::Md6;
+    /// let mut state = Md6::with_key(256, b"s3cr3t key").unwrap();
+    /// state.update(b"The lazy fox jumps over the lazy dog");
+    /// assert_eq!(state.finalise_hex(),
+    ///            "dfec88292911d4a2afaca754b80f13f452be59a0661471ae5b1417020089aeaf");
+    /// ```
+    pub fn finalise_hex(&mut self) -> String {
+        let outlen = ((native::hash_state_d(self.raw_state) as usize) + 7) / 8;
+        self.finalise(&mut vec![0; outlen]);
+
+        let hex = unsafe { std::slice::from_raw_parts(native::hash_state_hexhashval(self.raw_state), outlen * 2) };
+        String::from_utf8_lossy(hex).into_owned()
+    }
+}
+
+/// Hash a chunk of data with a 256-bit MD6 hash function, returning the digest.
+///
+/// Returns the digest by value as a `[u8; 32]`, so the 256-bit length is fixed by the type.
+///
+/// # Example
+///
+/// ```
+/// # use md6::Md6;
+/// assert_eq!(&md6::hash256(b"The lazy fox jumps over the lazy dog")[..8],
+///            &[0xE4, 0x55, 0x51, 0xAA, 0xE2, 0x66, 0xE1, 0x48]);
+/// ```
+pub fn hash256(data: &[u8]) -> [u8; 32] {
+    let mut hashval = [0; 32];
+    hash(256, data, &mut hashval).unwrap();
+    hashval
+}
+
+/// Hash a chunk of data with a 512-bit MD6 hash function, returning the digest.
+///
+/// The 512-bit counterpart of [`hash256()`](fn.hash256.html), yielding a `[u8; 64]`.
+///
+/// # Example
+///
+/// ```
+/// # use md6::Md6;
+/// assert_eq!(md6::hash512(b"The lazy fox jumps over the lazy dog.").len(), 64);
+/// ```
+pub fn hash512(data: &[u8]) -> [u8; 64] {
+    let mut hashval = [0; 64];
+    hash(512, data, &mut hashval).unwrap();
+    hashval
 }
 
 /// The `Write` implementation updates the state with the provided data.
@@ -341,6 +628,30 @@ impl io::Write for Md6 {
     }
 }
 
+/// Forking a partially-updated state, e.g. to hash many messages sharing a common prefix.
+///
+/// ```
+/// # use std::iter::FromIterator;
+/// # use md6::Md6;
+/// let mut prefix = Md6::new(256).unwrap();
+/// prefix.update(b"shared header");
+///
+/// let mut a = prefix.clone();
+/// let mut b = prefix.clone();
+/// a.update(b" + branch A");
+/// b.update(b" + branch B");
+///
+/// let (mut ra, mut rb) = ([0; 32], [0; 32]);
+/// a.finalise(&mut ra);
+/// b.finalise(&mut rb);
+/// assert!(ra != rb);
+/// ```
+impl Clone for Md6 {
+    fn clone(&self) -> Md6 {
+        Md6 { raw_state: native::clone_hash_state(self.raw_state) }
+    }
+}
+
 impl Drop for Md6 {
     fn drop(&mut self) {
         native::free_hash_state(&mut self.raw_state);
@@ -348,23 +659,131 @@ impl Drop for Md6 {
 }
 
 
+/// An adapter plugging MD6 into the standard [`Hasher`](https://doc.rust-lang.org/std/hash/trait.Hasher.html)
+/// trait, for use as a general-purpose (optionally keyed) hasher behind
+/// [`HashMap`](https://doc.rust-lang.org/std/collections/struct.HashMap.html) and friends.
+///
+/// Mirrors the way `SipHash` is exposed: [`write()`](#method.write) feeds bytes into the underlying
+/// state and [`finish()`](#method.finish) folds the leading 8 bytes of the digest into a `u64`
+/// without consuming the live state, so further writes may follow.
+///
+/// Usually constructed through [`Md6BuildHasher`](struct.Md6BuildHasher.html) rather than directly.
+pub struct Md6Hasher {
+    state: Md6,
+    hashbitlen: i32,
+}
+
+impl Md6Hasher {
+    /// Create an unkeyed hasher with the given output bit length.
+    ///
+    /// Errors exactly as [`Md6::new()`](struct.Md6.html#method.new).
+    pub fn new(hashbitlen: i32) -> Result<Md6Hasher> {
+        Ok(Md6Hasher { state: Md6::new(hashbitlen)?, hashbitlen: hashbitlen })
+    }
+
+    /// Create a keyed hasher with the given output bit length and key.
+    ///
+    /// Errors exactly as [`Md6::with_key()`](struct.Md6.html#method.with_key).
+    pub fn with_key(hashbitlen: i32, key: &[u8]) -> Result<Md6Hasher> {
+        Ok(Md6Hasher { state: Md6::with_key(hashbitlen, key)?, hashbitlen: hashbitlen })
+    }
+}
+
+impl Hasher for Md6Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.state.update(bytes);
+    }
+
+    /// Finalise a snapshot of the current state and fold the leading bytes of the digest into a
+    /// `u64`; the live state is left untouched so more data may be written afterwards.
+    fn finish(&self) -> u64 {
+        let mut snapshot = Md6 { raw_state: native::clone_hash_state(self.state.raw_state) };
+
+        let mut digest = vec![0; ((self.hashbitlen as usize) + 7) / 8];
+        snapshot.finalise(&mut digest);
+
+        let mut out = 0u64;
+        for &b in digest.iter().take(8) {
+            out = (out << 8) | b as u64;
+        }
+        out
+    }
+}
+
+
+/// A [`BuildHasher`](https://doc.rust-lang.org/std/hash/trait.BuildHasher.html) producing
+/// [`Md6Hasher`](struct.Md6Hasher.html)s with a fixed output length and optional key.
+///
+/// This is what lets (keyed) MD6 be dropped into
+/// [`HashMap`](https://doc.rust-lang.org/std/collections/struct.HashMap.html)/`HashSet`:
+///
+/// ```
+/// # use md6::Md6BuildHasher;
+/// # use std::collections::HashMap;
+/// let mut map = HashMap::with_hasher(Md6BuildHasher::with_key(256, b"s3cr3t").unwrap());
+/// map.insert("key", "value");
+/// assert_eq!(map.get("key"), Some(&"value"));
+/// ```
+#[derive(Clone)]
+pub struct Md6BuildHasher {
+    hashbitlen: i32,
+    key: Option<Vec<u8>>,
+}
+
+impl Md6BuildHasher {
+    /// Build unkeyed hashers with the given output bit length.
+    ///
+    /// The `hashbitlen` is validated eagerly, erroring exactly as [`Md6::new()`](struct.Md6.html#method.new).
+    pub fn new(hashbitlen: i32) -> Result<Md6BuildHasher> {
+        Md6::new(hashbitlen)?;
+        Ok(Md6BuildHasher { hashbitlen: hashbitlen, key: None })
+    }
+
+    /// Build keyed hashers with the given output bit length and key.
+    ///
+    /// The parameters are validated eagerly, erroring exactly as
+    /// [`Md6::with_key()`](struct.Md6.html#method.with_key).
+    pub fn with_key(hashbitlen: i32, key: &[u8]) -> Result<Md6BuildHasher> {
+        Md6::with_key(hashbitlen, key)?;
+        Ok(Md6BuildHasher { hashbitlen: hashbitlen, key: Some(key.to_vec()) })
+    }
+}
+
+impl BuildHasher for Md6BuildHasher {
+    type Hasher = Md6Hasher;
+
+    fn build_hasher(&self) -> Md6Hasher {
+        match self.key {
+                Some(ref key) => Md6Hasher::with_key(self.hashbitlen, key),
+                None => Md6Hasher::new(self.hashbitlen),
+            }
+            .expect("parameters validated at construction")
+    }
+}
+
+
 impl Error for Md6Error {
     fn description(&self) -> &str {
         match self {
             &Md6Error::Fail => "Generic MD6 fail",
             &Md6Error::BadHashbitlen => "Incorrect hashbitlen",
+            &Md6Error::BadKeylen => "Incorrect key length",
         }
     }
 }
 
 impl From<i32> for Md6Error {
-    /// Passing incorrect error values yields unspecified behaviour.
+    /// `0` is not an error and panics; every other reference error code maps to a variant.
+    ///
+    /// Besides the NIST wrapper's `FAIL`/`BADHASHLEN`, `md6_full_init` (reached through
+    /// `with_key`/`with_params`) can return further codes such as `BADKEYLEN`; those that do not
+    /// have a dedicated variant collapse into `Fail` rather than aborting.
     fn from(i: i32) -> Self {
         match i {
             0 => panic!("Not an error"),
-            1 => Md6Error::Fail,
             2 => Md6Error::BadHashbitlen,
-            _ => panic!("Incorrect error number"),
+            4 => Md6Error::BadKeylen,
+            _ => Md6Error::Fail,
         }
     }
 }
@@ -46,6 +46,8 @@ extern "C" {
     pub fn MD6_Hash_Final(state: FFIHashState, hashval: *mut u8) -> c_int;
 
     pub fn MD6_Hash_Hash(hashbitlen: c_int, data: *const u8, databitlen: u64, hashval: *mut u8) -> c_int;
+
+    pub fn md6_full_init(state: FFIHashState, d: c_int, key: *const u8, keylen: c_int, L: c_int, r: c_int) -> c_int;
 }
 
 pub fn malloc_hash_state() -> FFIHashState {
@@ -56,3 +58,27 @@ pub fn free_hash_state(state: &mut FFIHashState) {
     unsafe { free(*state) };
     *state = null_mut();
 }
+
+/// Read the output length in bits (`d`) the state was initialised with.
+///
+/// `d` is set by every init path (`MD6_Hash_Init`, `md6_full_init`) and is what `md6_final` uses
+/// to size its output, unlike `hashbitlen` which only the NIST wrapper populates.
+pub fn hash_state_d(state: FFIHashState) -> c_int {
+    unsafe { (*(state as *const md6_state)).d }
+}
+
+/// Borrow the state's `hexhashval` buffer, which the reference fills during finalisation.
+pub fn hash_state_hexhashval(state: FFIHashState) -> *const u8 {
+    unsafe { (*(state as *const md6_state)).hexhashval.as_ptr() }
+}
+
+/// Duplicate a hash state into a freshly `malloc`ated one.
+///
+/// `md6_state` is a flat POD with no internal pointers, so a bytewise copy is sound.
+pub fn clone_hash_state(state: FFIHashState) -> FFIHashState {
+    let fresh = malloc_hash_state();
+    unsafe {
+        std::ptr::copy_nonoverlapping(state as *const u8, fresh as *mut u8, size_of::<md6_state>());
+    }
+    fresh
+}